@@ -1,22 +1,174 @@
 use anyhow::{anyhow, bail, Context, Result};
 use async_compression::futures::bufread::GzipDecoder;
 use async_tar::Archive;
-use futures::AsyncReadExt;
-use semver::Version;
+use futures::{AsyncReadExt, AsyncWriteExt};
+use semver::{Version, VersionReq};
 use serde::Deserialize;
 use serde_json::Value;
+use sha2::{Digest, Sha256, Sha512};
 use smol::{fs, io::BufReader, lock::Mutex, process::Command};
 use std::process::{Output, Stdio};
+use thiserror::Error;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
 use std::{
     env::consts,
     path::{Path, PathBuf},
+    str::FromStr,
     sync::Arc,
 };
-use util::http::HttpClient;
+use util::http::{self, HttpClient};
 use util::ResultExt;
 
 const VERSION: &str = "v18.15.0";
 
+/// A typed failure from the Node runtime. Callers that only want a message can
+/// `to_string()` it; retry and offline-fallback logic can match on the variant.
+#[derive(Debug, Error)]
+pub enum NodeRuntimeError {
+    #[error("running on unsupported os: {0}")]
+    UnsupportedOs(String),
+    #[error("running on unsupported architecture: {0}")]
+    UnsupportedArch(String),
+    #[error("network error: {0}")]
+    Network(#[source] anyhow::Error),
+    #[error("checksum mismatch for {file}: expected {expected}, got {actual}")]
+    ChecksumMismatch {
+        file: String,
+        expected: String,
+        actual: String,
+    },
+    #[error("failed to extract archive: {0}")]
+    Extraction(#[source] anyhow::Error),
+    #[error("npm {subcommand} exited with a failure")]
+    NpmSubcommandFailed {
+        subcommand: String,
+        stdout: String,
+        stderr: String,
+    },
+    #[error("no version found for {0}")]
+    VersionNotFound(String),
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+impl NodeRuntimeError {
+    /// Human-facing help text for the failure, suitable for surfacing in the UI
+    /// alongside the error message.
+    pub fn help(&self) -> &'static str {
+        match self {
+            NodeRuntimeError::UnsupportedOs(_) => {
+                "Zed does not ship a Node build for this operating system."
+            }
+            NodeRuntimeError::UnsupportedArch(_) => {
+                "Zed does not ship a Node build for this CPU architecture."
+            }
+            NodeRuntimeError::Network(_) => {
+                "The Node registry is unreachable. Check your connection or proxy and retry."
+            }
+            NodeRuntimeError::ChecksumMismatch { .. } => {
+                "The download was corrupted or tampered with. Retry the install."
+            }
+            NodeRuntimeError::Extraction(_) => {
+                "The downloaded archive could not be extracted. Retry the install."
+            }
+            NodeRuntimeError::NpmSubcommandFailed { .. } => {
+                "npm reported an error. See its output for details."
+            }
+            NodeRuntimeError::VersionNotFound(_) => {
+                "No published version matched the requested range."
+            }
+            NodeRuntimeError::Other(_) => "An unexpected error occurred.",
+        }
+    }
+}
+
+/// How long a cached copy of the `nodejs.org` release index is trusted before
+/// we re-fetch it. Launches that happen inside this window are served entirely
+/// from disk so repeated startups don't re-hit the network.
+const INDEX_TTL: Duration = Duration::from_secs(60 * 60 * 12);
+
+/// A request for a particular Node version, mirroring the spellings `nenv` and
+/// other version managers accept.
+#[derive(Debug, Clone)]
+pub enum NodeVersionReq {
+    /// The version bundled with this build. Resolved to [`VERSION`] directly,
+    /// without consulting the release index, so the default install path keeps
+    /// the baseline's no-network behaviour.
+    Bundled,
+    /// The single greatest published release.
+    Latest,
+    /// The greatest release that belongs to any LTS line.
+    Lts,
+    /// The greatest release of a named LTS line, e.g. `hydrogen`.
+    LtsLine(String),
+    /// The greatest release satisfying a semver range.
+    Range(VersionReq),
+}
+
+impl NodeVersionReq {
+    /// The version installed when a project makes no explicit request,
+    /// preserving the previously hardcoded `VERSION` pin.
+    fn default_pin() -> Self {
+        NodeVersionReq::Bundled
+    }
+}
+
+impl FromStr for NodeVersionReq {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let trimmed = s.trim();
+        match trimmed.to_ascii_lowercase().as_str() {
+            "latest" => Ok(Self::Latest),
+            "lts" => Ok(Self::Lts),
+            _ => {
+                if let Ok(req) = VersionReq::parse(trimmed) {
+                    Ok(Self::Range(req))
+                } else {
+                    // Anything that isn't `latest`, `lts`, or a semver range is
+                    // treated as an LTS codename (e.g. `hydrogen`).
+                    Ok(Self::LtsLine(trimmed.to_string()))
+                }
+            }
+        }
+    }
+}
+
+/// A single entry of the `https://nodejs.org/dist/index.json` document.
+#[derive(Debug, Deserialize)]
+struct NodeDistRelease {
+    version: String,
+    #[serde(default)]
+    lts: NodeLts,
+    #[serde(default)]
+    files: Vec<String>,
+}
+
+/// The `lts` field of a release: either `false` for a non-LTS release or the
+/// line's codename for an LTS one.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum NodeLts {
+    Named(String),
+    Flag(bool),
+}
+
+impl Default for NodeLts {
+    fn default() -> Self {
+        NodeLts::Flag(false)
+    }
+}
+
+impl NodeLts {
+    fn codename(&self) -> Option<&str> {
+        match self {
+            NodeLts::Named(name) => Some(name),
+            NodeLts::Flag(_) => None,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct NpmInfo {
@@ -30,21 +182,218 @@ pub struct NpmInfoDistTags {
     latest: Option<String>,
 }
 
+/// The abbreviated registry document returned for
+/// `Accept: application/vnd.npm.install-v1+json`.
+#[derive(Debug, Deserialize)]
+pub struct NpmRegistryPackage {
+    #[serde(rename = "dist-tags", default)]
+    pub dist_tags: HashMap<String, String>,
+    #[serde(default)]
+    pub versions: HashMap<String, NpmVersionInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NpmVersionInfo {
+    pub version: String,
+    #[serde(default)]
+    pub dist: NpmDist,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct NpmDist {
+    #[serde(default)]
+    pub tarball: String,
+    #[serde(default)]
+    pub integrity: Option<String>,
+}
+
+/// A `package-lock.json` document. Only the fields needed to reconstruct the
+/// set of tarballs are modelled.
+#[derive(Debug, Deserialize, Default)]
+struct PackageLock {
+    #[serde(rename = "lockfileVersion", default)]
+    lockfile_version: u32,
+    /// Flat package map used by v2/v3 lockfiles, keyed by install path.
+    #[serde(default)]
+    packages: HashMap<String, LockPackage>,
+    /// Nested dependency tree used by v1 lockfiles.
+    #[serde(default)]
+    dependencies: HashMap<String, LockDependency>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LockPackage {
+    resolved: Option<String>,
+    integrity: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LockDependency {
+    resolved: Option<String>,
+    integrity: Option<String>,
+    #[serde(default)]
+    bundled: bool,
+    #[serde(default)]
+    dependencies: HashMap<String, LockDependency>,
+}
+
+/// The `nodejs.org` download os token for the current platform (`darwin`,
+/// `linux`, `win`).
+fn node_os() -> Result<&'static str, NodeRuntimeError> {
+    Ok(match consts::OS {
+        "macos" => "darwin",
+        "linux" => "linux",
+        "windows" => "win",
+        other => return Err(NodeRuntimeError::UnsupportedOs(other.to_string())),
+    })
+}
+
+/// The `nodejs.org` download arch token for the current platform (`x64`,
+/// `arm64`).
+fn node_arch() -> Result<&'static str, NodeRuntimeError> {
+    Ok(match consts::ARCH {
+        "x86_64" => "x64",
+        "aarch64" => "arm64",
+        other => return Err(NodeRuntimeError::UnsupportedArch(other.to_string())),
+    })
+}
+
+/// The token used in a release's `files` list for the tarball we download on
+/// the current platform. `index.json` spells macOS as `osx` and suffixes the
+/// archive kind, so this differs from [`node_os`].
+fn node_file_token() -> Result<String, NodeRuntimeError> {
+    let arch = node_arch()?;
+    Ok(match consts::OS {
+        "macos" => format!("osx-{arch}-tar"),
+        "linux" => format!("linux-{arch}"),
+        "windows" => format!("win-{arch}-zip"),
+        other => return Err(NodeRuntimeError::UnsupportedOs(other.to_string())),
+    })
+}
+
+/// Flatten a v1 lockfile's nested `dependencies` tree into the flat set of
+/// tarballs, deduping by resolved URL and skipping `bundled` entries (whose
+/// contents ship inside their parent's tarball).
+fn collect_v1_tarballs(
+    dependencies: &HashMap<String, LockDependency>,
+    out: &mut HashMap<String, String>,
+) {
+    for dependency in dependencies.values() {
+        if !dependency.bundled {
+            if let (Some(resolved), Some(integrity)) =
+                (&dependency.resolved, &dependency.integrity)
+            {
+                out.entry(resolved.clone())
+                    .or_insert_with(|| integrity.clone());
+            }
+        }
+        collect_v1_tarballs(&dependency.dependencies, out);
+    }
+}
+
+// The offline cache we populate below reconstructs npm's private on-disk
+// format by hand, so the layout is coupled to npm's internals. It targets the
+// cacache **index-v5** / **content-v2** schema and the `make-fetch-happen`
+// request-cache key format used by **npm 8–10** (make-fetch-happen 10–13,
+// cacache 16–18, i.e. the Node 16–20 line Zed ships against). If a future npm
+// changes the `make-fetch-happen:request-cache:{url}` key or the stored
+// response-metadata shape, `npm ci --offline` will fail to resolve from this
+// directory with a network error; bump this note and the two helpers below in
+// lockstep when revving the bundled Node/npm. There is no automated
+// `npm ci --offline` test here because CI has no Node toolchain to drive one.
+
+/// The cacache key under which npm's `make-fetch-happen` stores (and later
+/// looks up) the cached response for a tarball URL. Writing content under this
+/// key — rather than a bare content-addressed blob — is what lets
+/// `npm ci --offline` resolve the package from the cache index.
+fn tarball_cache_key(resolved: &str) -> String {
+    format!("make-fetch-happen:request-cache:{resolved}")
+}
+
+/// The `make-fetch-happen` response metadata cacache stores alongside a cached
+/// tarball. Without it the offline client treats the entry as unusable, so we
+/// synthesize the minimal shape a `200` tarball response would have carried.
+fn tarball_cache_metadata(resolved: &str) -> Value {
+    serde_json::json!({
+        "url": resolved,
+        "reqHeaders": {},
+        "resHeaders": {
+            "content-type": "application/octet-stream",
+        },
+        "status": 200,
+        "options": { "compress": true },
+    })
+}
+
+/// Compute the SRI string (`<algo>-<base64>`) of `bytes` using the algorithm
+/// named in `integrity`, for reporting the digest we actually observed.
+fn actual_sri(bytes: &[u8], integrity: &str) -> Result<String> {
+    let (algo, _) = integrity
+        .split_once('-')
+        .ok_or_else(|| anyhow!("malformed integrity string: {integrity}"))?;
+    let digest = match algo {
+        "sha512" => Sha512::digest(bytes).to_vec(),
+        "sha256" => Sha256::digest(bytes).to_vec(),
+        other => bail!("unsupported integrity algorithm: {other}"),
+    };
+    Ok(format!("{algo}-{}", base64::encode(digest)))
+}
+
+/// Verify that `bytes` hash to the digest encoded in an SRI `integrity` string.
+fn verify_integrity(bytes: &[u8], integrity: &str) -> Result<()> {
+    let (algo, encoded) = integrity
+        .split_once('-')
+        .ok_or_else(|| anyhow!("malformed integrity string: {integrity}"))?;
+    let expected = base64::decode(encoded)
+        .with_context(|| format!("malformed integrity digest: {integrity}"))?;
+    let actual = match algo {
+        "sha512" => Sha512::digest(bytes).to_vec(),
+        "sha256" => Sha256::digest(bytes).to_vec(),
+        other => bail!("unsupported integrity algorithm: {other}"),
+    };
+    if actual != expected {
+        bail!("integrity mismatch for {integrity}");
+    }
+    Ok(())
+}
+
 #[async_trait::async_trait]
 pub trait NodeRuntime: Send + Sync {
-    async fn binary_path(&self) -> Result<PathBuf>;
+    async fn binary_path(&self) -> Result<PathBuf, NodeRuntimeError>;
+
+    /// Resolve a [`NodeVersionReq`] to a concrete version string (e.g.
+    /// `v18.15.0`) against the published `nodejs.org` release index.
+    async fn resolve_node_version(
+        &self,
+        req: &NodeVersionReq,
+    ) -> Result<String, NodeRuntimeError>;
 
     async fn run_npm_subcommand(
         &self,
         directory: Option<&Path>,
         subcommand: &str,
         args: &[&str],
-    ) -> Result<Output>;
+    ) -> Result<Output, NodeRuntimeError>;
+
+    async fn npm_package_latest_version(&self, name: &str) -> Result<String, NodeRuntimeError>;
 
-    async fn npm_package_latest_version(&self, name: &str) -> Result<String>;
+    /// Resolve the greatest published version of `name` satisfying `req`.
+    async fn npm_package_version_satisfying(
+        &self,
+        name: &str,
+        req: &VersionReq,
+    ) -> Result<String, NodeRuntimeError>;
+
+    async fn npm_install_packages(
+        &self,
+        directory: &Path,
+        packages: &[(&str, &str)],
+    ) -> Result<(), NodeRuntimeError>;
 
-    async fn npm_install_packages(&self, directory: &Path, packages: &[(&str, &str)])
-        -> Result<()>;
+    /// Install the dependencies pinned by the `package-lock.json` in
+    /// `directory`, verifying every tarball's integrity and populating an
+    /// offline npm cache so the install is reproducible and air-gap friendly.
+    async fn npm_install_from_lockfile(&self, directory: &Path) -> Result<(), NodeRuntimeError>;
 
     async fn should_install_npm_package(
         &self,
@@ -97,37 +446,272 @@ pub trait NodeRuntime: Send + Sync {
     }
 }
 
+/// npm/registry configuration, chiefly for users behind a corporate registry
+/// or proxy. An all-`None`/empty value reproduces the previous blank-npmrc,
+/// default-registry behaviour.
+#[derive(Debug, Clone, Default)]
+pub struct NpmConfig {
+    /// Default registry URL (`registry=...`).
+    pub registry: Option<String>,
+    /// Per-scope registry overrides, e.g. `("@acme", "https://npm.acme.com")`.
+    pub scoped_registries: Vec<(String, String)>,
+    /// Auth token applied to the default registry.
+    pub auth_token: Option<String>,
+    /// HTTP/HTTPS proxy URL.
+    pub proxy: Option<String>,
+}
+
+impl NpmConfig {
+    /// The registry base URL to use for direct HTTP fetches.
+    fn registry_base(&self) -> &str {
+        self.registry
+            .as_deref()
+            .map(|url| url.trim_end_matches('/'))
+            .unwrap_or("https://registry.npmjs.org")
+    }
+
+    /// The registry base URL for `name`, honoring a scoped-registry override
+    /// when the package carries a matching `@scope/` prefix.
+    fn registry_base_for(&self, name: &str) -> &str {
+        if let Some(rest) = name.strip_prefix('@') {
+            if let Some(scope) = rest.split('/').next() {
+                let scope = format!("@{scope}");
+                if let Some((_, url)) = self
+                    .scoped_registries
+                    .iter()
+                    .find(|(configured, _)| *configured == scope)
+                {
+                    return url.trim_end_matches('/');
+                }
+            }
+        }
+        self.registry_base()
+    }
+
+    /// The auth token to attach to a direct fetch for `name`. The single
+    /// configured token authenticates the default registry, so it is only sent
+    /// when the request actually targets that registry (not a scoped override).
+    fn auth_token_for(&self, name: &str) -> Option<&str> {
+        match &self.auth_token {
+            Some(token) if self.registry_base_for(name) == self.registry_base() => Some(token),
+            _ => None,
+        }
+    }
+
+    /// Render an `npmrc` reflecting this configuration.
+    fn render_npmrc(&self) -> String {
+        let mut lines = Vec::new();
+        if let Some(registry) = &self.registry {
+            lines.push(format!("registry={registry}"));
+        }
+        for (scope, url) in &self.scoped_registries {
+            lines.push(format!("{scope}:registry={url}"));
+        }
+        if let Some(token) = &self.auth_token {
+            // npm keys auth tokens by the registry host, without the scheme.
+            let host = self
+                .registry_base()
+                .split_once("//")
+                .map_or(self.registry_base(), |(_, rest)| rest);
+            lines.push(format!("//{host}/:_authToken={token}"));
+        }
+        if let Some(proxy) = &self.proxy {
+            lines.push(format!("proxy={proxy}"));
+            lines.push(format!("https-proxy={proxy}"));
+        }
+        let mut rendered = lines.join("\n");
+        if !rendered.is_empty() {
+            rendered.push('\n');
+        }
+        rendered
+    }
+}
+
 pub struct RealNodeRuntime {
     http: Arc<dyn HttpClient>,
+    config: NpmConfig,
+    version_req: NodeVersionReq,
     installation_lock: Mutex<()>,
 }
 
 impl RealNodeRuntime {
     pub fn new(http: Arc<dyn HttpClient>) -> Arc<dyn NodeRuntime> {
+        Self::new_with_config(http, NpmConfig::default())
+    }
+
+    pub fn new_with_config(http: Arc<dyn HttpClient>, config: NpmConfig) -> Arc<dyn NodeRuntime> {
+        Self::new_with_version(http, config, NodeVersionReq::default_pin())
+    }
+
+    /// Construct a runtime that installs the greatest Node version satisfying
+    /// `version_req` instead of the pinned default.
+    pub fn new_with_version(
+        http: Arc<dyn HttpClient>,
+        config: NpmConfig,
+        version_req: NodeVersionReq,
+    ) -> Arc<dyn NodeRuntime> {
+        // The proxy lives on the HTTP client in this codebase, so a configured
+        // proxy has to be baked into a dedicated client here; otherwise the
+        // direct registry/dist fetches below would bypass it even though the
+        // npm subprocess honors `--proxy`.
+        let http = match config.proxy.as_deref() {
+            Some(proxy) => match proxy.parse::<http::Uri>() {
+                Ok(uri) => http::client(Some(uri)),
+                Err(error) => {
+                    log::warn!("ignoring malformed npm proxy {proxy:?}: {error}");
+                    http
+                }
+            },
+            None => http,
+        };
+
         Arc::new(RealNodeRuntime {
             http,
+            config,
+            version_req,
             installation_lock: Mutex::new(()),
         })
     }
 
-    async fn install_if_needed(&self) -> Result<PathBuf> {
-        let _lock = self.installation_lock.lock().await;
-        log::info!("Node runtime install_if_needed");
+    /// Resolve the configured [`NodeVersionReq`] to the concrete version we
+    /// should install. Falls back to the pinned [`VERSION`] when the release
+    /// index can't be reached so offline launches still work.
+    async fn resolved_install_version(&self) -> String {
+        // The default pin never needs the release index; resolve it without a
+        // network round-trip so a fresh machine installs Node as fast as the
+        // baseline did.
+        if let NodeVersionReq::Bundled = self.version_req {
+            return VERSION.to_string();
+        }
+        match self.resolve_node_version(&self.version_req).await {
+            Ok(version) => version,
+            Err(error) => {
+                log::warn!("falling back to pinned Node {VERSION}: {error}");
+                VERSION.to_string()
+            }
+        }
+    }
 
-        let os = match consts::OS {
-            "macos" => "darwin",
-            "linux" => "linux",
-            "windows" => "win",
-            other => bail!("Running on unsupported os: {other}"),
-        };
+    /// Fetch and parse the `nodejs.org` release index, serving it from a
+    /// short-lived on-disk cache and falling back to that cache when the
+    /// network is unavailable.
+    async fn dist_index(&self) -> Result<Vec<NodeDistRelease>> {
+        let cache_path = util::paths::SUPPORT_DIR.join("node").join("index.json");
 
-        let arch = match consts::ARCH {
-            "x86_64" => "x64",
-            "aarch64" => "arm64",
-            other => bail!("Running on unsupported architecture: {other}"),
+        let fresh = match fs::metadata(&cache_path).await {
+            Ok(metadata) => metadata
+                .modified()
+                .ok()
+                .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+                .map_or(false, |age| age < INDEX_TTL),
+            Err(_) => false,
         };
 
-        let folder_name = format!("node-{VERSION}-{os}-{arch}");
+        if fresh {
+            if let Some(bytes) = fs::read(&cache_path).await.log_err() {
+                if let Some(index) = serde_json::from_slice(&bytes).log_err() {
+                    return Ok(index);
+                }
+            }
+        }
+
+        let fetched = async {
+            let mut response = self
+                .http
+                .get("https://nodejs.org/dist/index.json", Default::default(), true)
+                .await
+                .context("error fetching Node release index")?;
+            let mut body = Vec::new();
+            response.body_mut().read_to_end(&mut body).await?;
+            anyhow::Ok(body)
+        }
+        .await;
+
+        match fetched {
+            Ok(body) => {
+                let index: Vec<NodeDistRelease> =
+                    serde_json::from_slice(&body).context("error parsing Node release index")?;
+                if let Some(parent) = cache_path.parent() {
+                    _ = fs::create_dir_all(parent).await;
+                }
+                _ = fs::write(&cache_path, &body).await;
+                Ok(index)
+            }
+            Err(error) => {
+                // Offline: fall back to whatever we cached previously, however stale.
+                let bytes = fs::read(&cache_path)
+                    .await
+                    .map_err(|_| error)
+                    .context("Node release index unavailable and no cached copy on disk")?;
+                serde_json::from_slice(&bytes).context("error parsing cached Node release index")
+            }
+        }
+    }
+
+    /// Fetch and parse `SHASUMS256.txt` for release `version`, returning the
+    /// expected SHA-256 of `file_name` as a lowercase hex string.
+    async fn node_tarball_sha256(&self, version: &str, file_name: &str) -> Result<String> {
+        let url = format!("https://nodejs.org/dist/{version}/SHASUMS256.txt");
+        let mut response = self
+            .http
+            .get(&url, Default::default(), true)
+            .await
+            .context("error downloading Node SHASUMS256.txt")?;
+        let mut body = String::new();
+        response
+            .body_mut()
+            .read_to_string(&mut body)
+            .await
+            .context("error reading Node SHASUMS256.txt")?;
+
+        body.lines()
+            .find_map(|line| {
+                let (hash, name) = line.split_once("  ")?;
+                (name.trim() == file_name).then(|| hash.trim().to_string())
+            })
+            .ok_or_else(|| anyhow!("no checksum found for {file_name} in SHASUMS256.txt"))
+    }
+
+    /// Fetch the abbreviated registry document for `name` directly over HTTP,
+    /// bypassing the `npm info` subprocess.
+    async fn fetch_npm_registry_package(&self, name: &str) -> Result<NpmRegistryPackage> {
+        let url = format!("{}/{name}", self.config.registry_base_for(name));
+        let mut builder = http::Request::builder()
+            .uri(&url)
+            .header("Accept", "application/vnd.npm.install-v1+json");
+        if let Some(token) = self.config.auth_token_for(name) {
+            builder = builder.header("Authorization", format!("Bearer {token}"));
+        }
+        let request = builder.body(Default::default())?;
+        let mut response = self
+            .http
+            .send(request)
+            .await
+            .with_context(|| format!("error querying npm registry for {name}"))?;
+        // Every field on `NpmRegistryPackage` is `#[serde(default)]`, so an
+        // error body (404 unknown package, 401 auth) would otherwise parse into
+        // an empty document and masquerade as "no versions". Fail loudly first.
+        if !response.status().is_success() {
+            bail!(
+                "npm registry returned {} for {name}",
+                response.status()
+            );
+        }
+        let mut body = Vec::new();
+        response.body_mut().read_to_end(&mut body).await?;
+        serde_json::from_slice(&body)
+            .with_context(|| format!("error parsing npm registry document for {name}"))
+    }
+
+    async fn install_if_needed(&self) -> Result<PathBuf, NodeRuntimeError> {
+        let _lock = self.installation_lock.lock().await;
+        log::info!("Node runtime install_if_needed");
+
+        let os = node_os()?;
+        let arch = node_arch()?;
+
+        let version = self.resolved_install_version().await;
+        let folder_name = format!("node-{version}-{os}-{arch}");
         let node_containing_dir = util::paths::SUPPORT_DIR.join("node");
         let node_dir = node_containing_dir.join(folder_name);
         let node_binary = node_dir.join("bin/node");
@@ -153,22 +737,53 @@ impl RealNodeRuntime {
                 .await
                 .context("error creating node containing dir")?;
 
-            let file_name = format!("node-{VERSION}-{os}-{arch}.tar.gz");
-            let url = format!("https://nodejs.org/dist/{VERSION}/{file_name}");
+            let file_name = format!("node-{version}-{os}-{arch}.tar.gz");
+            let url = format!("https://nodejs.org/dist/{version}/{file_name}");
             let mut response = self
                 .http
                 .get(&url, Default::default(), true)
                 .await
-                .context("error downloading Node binary tarball")?;
+                .context("error downloading Node binary tarball")
+                .map_err(NodeRuntimeError::Network)?;
+
+            // Buffer the whole tarball so we can verify its checksum before it
+            // touches disk; a corrupted or tampered download must never unpack.
+            let mut tarball = Vec::new();
+            response
+                .body_mut()
+                .read_to_end(&mut tarball)
+                .await
+                .context("error reading Node binary tarball")
+                .map_err(NodeRuntimeError::Network)?;
+
+            let expected = self.node_tarball_sha256(&version, &file_name).await?;
+            let actual = {
+                let mut hasher = Sha256::new();
+                hasher.update(&tarball);
+                hex::encode(hasher.finalize())
+            };
+            if actual != expected {
+                return Err(NodeRuntimeError::ChecksumMismatch {
+                    file: file_name,
+                    expected,
+                    actual,
+                });
+            }
 
-            let decompressed_bytes = GzipDecoder::new(BufReader::new(response.body_mut()));
+            let decompressed_bytes = GzipDecoder::new(BufReader::new(&tarball[..]));
             let archive = Archive::new(decompressed_bytes);
-            archive.unpack(&node_containing_dir).await?;
+            archive
+                .unpack(&node_containing_dir)
+                .await
+                .map_err(|error| NodeRuntimeError::Extraction(error.into()))?;
         }
 
         // Note: Not in the `if !valid {}` so we can populate these for existing installations
         _ = fs::create_dir(node_dir.join("cache")).await;
-        _ = fs::write(node_dir.join("blank_user_npmrc"), []).await;
+        // When a registry/proxy configuration is present we write a real npmrc
+        // in place of the blank one so corporate registries and auth work.
+        let user_npmrc = self.config.render_npmrc();
+        _ = fs::write(node_dir.join("blank_user_npmrc"), user_npmrc.as_bytes()).await;
         _ = fs::write(node_dir.join("blank_global_npmrc"), []).await;
 
         anyhow::Ok(node_dir)
@@ -177,17 +792,70 @@ impl RealNodeRuntime {
 
 #[async_trait::async_trait]
 impl NodeRuntime for RealNodeRuntime {
-    async fn binary_path(&self) -> Result<PathBuf> {
+    async fn binary_path(&self) -> Result<PathBuf, NodeRuntimeError> {
         let installation_path = self.install_if_needed().await?;
         Ok(installation_path.join("bin/node"))
     }
 
+    async fn resolve_node_version(
+        &self,
+        req: &NodeVersionReq,
+    ) -> Result<String, NodeRuntimeError> {
+        // The bundled pin is fixed at build time and needs no index lookup.
+        if let NodeVersionReq::Bundled = req {
+            return Ok(VERSION.to_string());
+        }
+
+        let file_token = node_file_token()?;
+        let index = self.dist_index().await.map_err(NodeRuntimeError::Network)?;
+
+        // Only releases that ship a build for the current platform are candidates.
+        let mut best: Option<(Version, String)> = None;
+        for release in &index {
+            if !release.files.iter().any(|file| file == &file_token) {
+                continue;
+            }
+
+            let matches = match req {
+                NodeVersionReq::Bundled => unreachable!("handled above"),
+                NodeVersionReq::Latest => true,
+                NodeVersionReq::Lts => release.lts.codename().is_some(),
+                NodeVersionReq::LtsLine(line) => release
+                    .lts
+                    .codename()
+                    .map_or(false, |codename| codename.eq_ignore_ascii_case(line)),
+                NodeVersionReq::Range(_) => true,
+            };
+            if !matches {
+                continue;
+            }
+
+            let Some(version) = Version::parse(release.version.trim_start_matches('v')).log_err()
+            else {
+                continue;
+            };
+
+            if let NodeVersionReq::Range(range) = req {
+                if !range.matches(&version) {
+                    continue;
+                }
+            }
+
+            if best.as_ref().map_or(true, |(best, _)| &version > best) {
+                best = Some((version, release.version.clone()));
+            }
+        }
+
+        best.map(|(_, version)| version)
+            .ok_or_else(|| NodeRuntimeError::VersionNotFound(format!("{req:?}")))
+    }
+
     async fn run_npm_subcommand(
         &self,
         directory: Option<&Path>,
         subcommand: &str,
         args: &[&str],
-    ) -> Result<Output> {
+    ) -> Result<Output, NodeRuntimeError> {
         let attempt = || async move {
             let installation_path = self.install_if_needed().await?;
 
@@ -223,6 +891,13 @@ impl NodeRuntime for RealNodeRuntime {
                 "--globalconfig".into(),
                 installation_path.join("blank_global_npmrc"),
             ]);
+            if let Some(registry) = &self.config.registry {
+                command.args(["--registry", registry]);
+            }
+            if let Some(proxy) = &self.config.proxy {
+                command.args(["--proxy", proxy]);
+                command.args(["--https-proxy", proxy]);
+            }
             command.args(args);
 
             if let Some(directory) = directory {
@@ -237,26 +912,33 @@ impl NodeRuntime for RealNodeRuntime {
         if output.is_err() {
             output = attempt().await;
             if output.is_err() {
-                return Err(anyhow!(
-                    "failed to launch npm subcommand {subcommand} subcommand"
-                ));
+                return Err(NodeRuntimeError::Other(anyhow!(
+                    "failed to launch npm {subcommand} subcommand"
+                )));
             }
         }
 
-        if let Ok(output) = &output {
-            if !output.status.success() {
-                return Err(anyhow!(
-                    "failed to execute npm {subcommand} subcommand:\nstdout: {:?}\nstderr: {:?}",
-                    String::from_utf8_lossy(&output.stdout),
-                    String::from_utf8_lossy(&output.stderr)
-                ));
-            }
+        let output = output.map_err(NodeRuntimeError::Other)?;
+        if !output.status.success() {
+            return Err(NodeRuntimeError::NpmSubcommandFailed {
+                subcommand: subcommand.to_string(),
+                stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+                stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+            });
         }
 
-        output.map_err(|e| anyhow!("{e}"))
+        Ok(output)
     }
 
-    async fn npm_package_latest_version(&self, name: &str) -> Result<String> {
+    async fn npm_package_latest_version(&self, name: &str) -> Result<String, NodeRuntimeError> {
+        // Prefer the registry REST API, which needs no working Node install
+        // and saves a process spawn. Fall back to `npm info` if it fails.
+        if let Some(package) = self.fetch_npm_registry_package(name).await.log_err() {
+            if let Some(latest) = package.dist_tags.get("latest") {
+                return Ok(latest.clone());
+            }
+        }
+
         let output = self
             .run_npm_subcommand(
                 None,
@@ -274,18 +956,39 @@ impl NodeRuntime for RealNodeRuntime {
             )
             .await?;
 
-        let mut info: NpmInfo = serde_json::from_slice(&output.stdout)?;
+        let mut info: NpmInfo = serde_json::from_slice(&output.stdout).map_err(anyhow::Error::from)?;
         info.dist_tags
             .latest
             .or_else(|| info.versions.pop())
-            .ok_or_else(|| anyhow!("no version found for npm package {}", name))
+            .ok_or_else(|| NodeRuntimeError::VersionNotFound(name.to_string()))
+    }
+
+    async fn npm_package_version_satisfying(
+        &self,
+        name: &str,
+        req: &VersionReq,
+    ) -> Result<String, NodeRuntimeError> {
+        let package = self
+            .fetch_npm_registry_package(name)
+            .await
+            .map_err(NodeRuntimeError::Network)?;
+        package
+            .versions
+            .keys()
+            .filter_map(|version| {
+                let parsed = Version::parse(version).ok()?;
+                req.matches(&parsed).then_some((parsed, version))
+            })
+            .max_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_, version)| version.clone())
+            .ok_or_else(|| NodeRuntimeError::VersionNotFound(format!("{name}@{req}")))
     }
 
     async fn npm_install_packages(
         &self,
         directory: &Path,
         packages: &[(&str, &str)],
-    ) -> Result<()> {
+    ) -> Result<(), NodeRuntimeError> {
         let packages: Vec<_> = packages
             .into_iter()
             .map(|(name, version)| format!("{name}@{version}"))
@@ -306,6 +1009,110 @@ impl NodeRuntime for RealNodeRuntime {
             .await?;
         Ok(())
     }
+
+    async fn npm_install_from_lockfile(&self, directory: &Path) -> Result<(), NodeRuntimeError> {
+        let lockfile_path = directory.join("package-lock.json");
+        let contents = fs::read(&lockfile_path)
+            .await
+            .with_context(|| format!("error reading {}", lockfile_path.display()))?;
+        let lockfile: PackageLock =
+            serde_json::from_slice(&contents).context("error parsing package-lock.json")?;
+
+        // Collect the unique tarballs, deduped by resolved URL.
+        let mut tarballs: HashMap<String, String> = HashMap::default();
+        if lockfile.lockfile_version >= 2 {
+            for package in lockfile.packages.values() {
+                if let (Some(resolved), Some(integrity)) =
+                    (&package.resolved, &package.integrity)
+                {
+                    tarballs
+                        .entry(resolved.clone())
+                        .or_insert_with(|| integrity.clone());
+                }
+            }
+        } else {
+            // v1 lockfiles store a nested tree instead of a flat package map.
+            collect_v1_tarballs(&lockfile.dependencies, &mut tarballs);
+        }
+
+        let cache_dir = directory.join(".npm-offline-cache");
+
+        for (resolved, integrity) in &tarballs {
+            let key = tarball_cache_key(resolved);
+            if cacache::metadata(&cache_dir, &key)
+                .await
+                .ok()
+                .flatten()
+                .is_some()
+            {
+                continue;
+            }
+
+            let mut response = self
+                .http
+                .get(resolved, Default::default(), true)
+                .await
+                .with_context(|| format!("error downloading {resolved}"))
+                .map_err(NodeRuntimeError::Network)?;
+            let mut bytes = Vec::new();
+            response
+                .body_mut()
+                .read_to_end(&mut bytes)
+                .await
+                .map_err(|error| NodeRuntimeError::Network(error.into()))?;
+
+            if verify_integrity(&bytes, integrity).is_err() {
+                // A genuine digest mismatch is surfaced as ChecksumMismatch so
+                // callers can match on it; a malformed/unknown SRI is a
+                // different, non-tamper failure and stays Other.
+                match actual_sri(&bytes, integrity) {
+                    Ok(actual) => {
+                        return Err(NodeRuntimeError::ChecksumMismatch {
+                            file: resolved.clone(),
+                            expected: integrity.clone(),
+                            actual,
+                        })
+                    }
+                    Err(error) => {
+                        return Err(NodeRuntimeError::Other(error.context(format!(
+                            "integrity check failed for {resolved}"
+                        ))))
+                    }
+                }
+            }
+
+            // Write through cacache so both the content-v2 blob and the
+            // index-v5 entry npm resolves against are populated, tagging the
+            // entry with the SRI digest and response metadata the offline
+            // client expects.
+            let sri = integrity
+                .parse::<cacache::Integrity>()
+                .with_context(|| format!("malformed integrity for {resolved}"))?;
+            let mut writer = cacache::WriteOpts::new()
+                .integrity(sri)
+                .metadata(tarball_cache_metadata(resolved))
+                .open(&cache_dir, &key)
+                .await
+                .context("error opening offline cache entry")?;
+            writer
+                .write_all(&bytes)
+                .await
+                .context("error writing offline cache entry")?;
+            writer
+                .commit()
+                .await
+                .context("error committing offline cache entry")?;
+        }
+
+        // With every tarball cached and verified, npm can install entirely offline.
+        self.run_npm_subcommand(
+            Some(directory),
+            "ci",
+            &["--offline", "--cache", &cache_dir.to_string_lossy()],
+        )
+        .await?;
+        Ok(())
+    }
 }
 
 pub struct FakeNodeRuntime;
@@ -318,28 +1125,224 @@ impl FakeNodeRuntime {
 
 #[async_trait::async_trait]
 impl NodeRuntime for FakeNodeRuntime {
-    async fn binary_path(&self) -> anyhow::Result<PathBuf> {
+    async fn binary_path(&self) -> Result<PathBuf, NodeRuntimeError> {
         unreachable!()
     }
 
+    async fn resolve_node_version(
+        &self,
+        req: &NodeVersionReq,
+    ) -> Result<String, NodeRuntimeError> {
+        unreachable!("Should not resolve Node version {req:?}")
+    }
+
     async fn run_npm_subcommand(
         &self,
         _: Option<&Path>,
         subcommand: &str,
         args: &[&str],
-    ) -> anyhow::Result<Output> {
+    ) -> Result<Output, NodeRuntimeError> {
         unreachable!("Should not run npm subcommand '{subcommand}' with args {args:?}")
     }
 
-    async fn npm_package_latest_version(&self, name: &str) -> anyhow::Result<String> {
+    async fn npm_package_latest_version(&self, name: &str) -> Result<String, NodeRuntimeError> {
         unreachable!("Should not query npm package '{name}' for latest version")
     }
 
+    async fn npm_package_version_satisfying(
+        &self,
+        name: &str,
+        req: &VersionReq,
+    ) -> Result<String, NodeRuntimeError> {
+        unreachable!("Should not query npm package '{name}' for version satisfying {req}")
+    }
+
     async fn npm_install_packages(
         &self,
         _: &Path,
         packages: &[(&str, &str)],
-    ) -> anyhow::Result<()> {
+    ) -> Result<(), NodeRuntimeError> {
         unreachable!("Should not install packages {packages:?}")
     }
+
+    async fn npm_install_from_lockfile(&self, directory: &Path) -> Result<(), NodeRuntimeError> {
+        unreachable!("Should not install from lockfile in {directory:?}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_version_requests() {
+        assert!(matches!(
+            NodeVersionReq::from_str("latest").unwrap(),
+            NodeVersionReq::Latest
+        ));
+        assert!(matches!(
+            NodeVersionReq::from_str("  LTS ").unwrap(),
+            NodeVersionReq::Lts
+        ));
+
+        // A bare codename is treated as an LTS line.
+        match NodeVersionReq::from_str("hydrogen").unwrap() {
+            NodeVersionReq::LtsLine(line) => assert_eq!(line, "hydrogen"),
+            other => panic!("expected LtsLine, got {other:?}"),
+        }
+
+        // Anything parseable as a semver range is a range request.
+        match NodeVersionReq::from_str("^18.15").unwrap() {
+            NodeVersionReq::Range(req) => {
+                assert!(req.matches(&Version::parse("18.16.0").unwrap()));
+                assert!(!req.matches(&Version::parse("19.0.0").unwrap()));
+            }
+            other => panic!("expected Range, got {other:?}"),
+        }
+
+        // The default pin is the bundled version, resolved without the index.
+        assert!(matches!(
+            NodeVersionReq::default_pin(),
+            NodeVersionReq::Bundled
+        ));
+    }
+
+    fn sri(algo: &str, bytes: &[u8]) -> String {
+        let digest = match algo {
+            "sha512" => Sha512::digest(bytes).to_vec(),
+            "sha256" => Sha256::digest(bytes).to_vec(),
+            other => panic!("unsupported algo {other}"),
+        };
+        format!("{algo}-{}", base64::encode(digest))
+    }
+
+    #[test]
+    fn verifies_integrity() {
+        let bytes = b"the quick brown fox";
+
+        // A matching digest passes for both supported algorithms.
+        verify_integrity(bytes, &sri("sha512", bytes)).unwrap();
+        verify_integrity(bytes, &sri("sha256", bytes)).unwrap();
+
+        // A digest computed over different bytes fails.
+        let mismatch = sri("sha512", b"something else");
+        assert!(verify_integrity(bytes, &mismatch).is_err());
+
+        // An unknown algorithm is rejected rather than silently accepted.
+        let encoded = base64::encode(Sha512::digest(bytes));
+        assert!(verify_integrity(bytes, &format!("sha1-{encoded}")).is_err());
+
+        // A malformed string (unknown algorithm, digest not valid base64) is an
+        // error, not a panic.
+        assert!(verify_integrity(bytes, "not-an-sri-really").is_err());
+    }
+
+    #[test]
+    fn flattens_v1_dependency_tree() {
+        let dep = |resolved: &str, bundled: bool, deps| LockDependency {
+            resolved: Some(resolved.to_string()),
+            integrity: Some(sri("sha512", resolved.as_bytes())),
+            bundled,
+            dependencies: deps,
+        };
+
+        let mut tree = HashMap::default();
+        tree.insert(
+            "a".to_string(),
+            dep(
+                "https://r/a.tgz",
+                false,
+                HashMap::from([
+                    // A nested dependency is collected too.
+                    ("b".to_string(), dep("https://r/b.tgz", false, HashMap::default())),
+                    // A bundled dependency is skipped (it ships in its parent).
+                    ("c".to_string(), dep("https://r/c.tgz", true, HashMap::default())),
+                ]),
+            ),
+        );
+        // A duplicate resolved URL is deduped.
+        tree.insert(
+            "a-again".to_string(),
+            dep("https://r/a.tgz", false, HashMap::default()),
+        );
+
+        let mut out = HashMap::default();
+        collect_v1_tarballs(&tree, &mut out);
+
+        let mut urls: Vec<_> = out.keys().cloned().collect();
+        urls.sort();
+        assert_eq!(urls, vec!["https://r/a.tgz", "https://r/b.tgz"]);
+    }
+
+    #[test]
+    fn renders_npmrc() {
+        // A blank config reproduces the previous empty npmrc.
+        assert_eq!(NpmConfig::default().render_npmrc(), "");
+
+        let config = NpmConfig {
+            registry: Some("https://npm.acme.com/".to_string()),
+            scoped_registries: vec![("@acme".to_string(), "https://npm.acme.com".to_string())],
+            auth_token: Some("sekret".to_string()),
+            proxy: Some("http://proxy.acme.com:8080".to_string()),
+        };
+        let rendered = config.render_npmrc();
+
+        assert!(rendered.contains("registry=https://npm.acme.com/"));
+        assert!(rendered.contains("@acme:registry=https://npm.acme.com"));
+        // The auth token is keyed by the registry host with the scheme stripped.
+        assert!(rendered.contains("//npm.acme.com/:_authToken=sekret"));
+        assert!(rendered.contains("proxy=http://proxy.acme.com:8080"));
+        assert!(rendered.contains("https-proxy=http://proxy.acme.com:8080"));
+        assert!(rendered.ends_with('\n'));
+    }
+
+    #[test]
+    fn selects_scoped_registry() {
+        let config = NpmConfig {
+            scoped_registries: vec![("@acme".to_string(), "https://npm.acme.com/".to_string())],
+            ..Default::default()
+        };
+        assert_eq!(config.registry_base_for("@acme/widget"), "https://npm.acme.com");
+        // Unscoped and unmatched-scope packages fall back to the default.
+        assert_eq!(config.registry_base_for("lodash"), "https://registry.npmjs.org");
+        assert_eq!(
+            config.registry_base_for("@other/thing"),
+            "https://registry.npmjs.org"
+        );
+    }
+
+    #[test]
+    fn scopes_auth_token_to_default_registry() {
+        let config = NpmConfig {
+            registry: Some("https://npm.acme.com".to_string()),
+            scoped_registries: vec![("@pub".to_string(), "https://registry.npmjs.org".to_string())],
+            auth_token: Some("sekret".to_string()),
+            ..Default::default()
+        };
+        // Packages served by the default (authed) registry get the token.
+        assert_eq!(config.auth_token_for("lodash"), Some("sekret"));
+        // A scoped override points elsewhere, so the token is withheld.
+        assert_eq!(config.auth_token_for("@pub/widget"), None);
+        // With no token configured, nothing is attached.
+        assert_eq!(NpmConfig::default().auth_token_for("lodash"), None);
+    }
+
+    #[test]
+    fn builds_make_fetch_happen_cache_entry() {
+        let url = "https://registry.npmjs.org/lodash/-/lodash-4.17.21.tgz";
+
+        // The key must match make-fetch-happen's request-cache namespace
+        // verbatim; npm looks the entry up by this exact string offline.
+        assert_eq!(
+            tarball_cache_key(url),
+            "make-fetch-happen:request-cache:https://registry.npmjs.org/lodash/-/lodash-4.17.21.tgz"
+        );
+
+        // The synthesized response metadata carries the fields the offline
+        // client requires to treat the entry as a usable 200 response.
+        let meta = tarball_cache_metadata(url);
+        assert_eq!(meta["status"], 200);
+        assert_eq!(meta["url"], url);
+        assert!(meta["resHeaders"]["content-type"].is_string());
+    }
 }